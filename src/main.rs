@@ -1,17 +1,58 @@
-use anyhow::{anyhow, Context, Error, Result};
+use anyhow::{Context, Error, Result};
+use async_stream::stream;
 use bigdecimal::BigDecimal;
 use csv::{ReaderBuilder, Trim};
-use log::error;
-use serde::Deserialize;
+use futures::{Stream, StreamExt};
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use structopt::StructOpt;
+use thiserror::Error as ThisError;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
 
 /// Command line options.
 #[derive(Debug, StructOpt)]
 #[structopt(about = "Simple toy payments engine in Rust")]
-struct Opt {
-    input_path: String,
+enum Opt {
+    /// Process a CSV file to completion and print the resulting accounts.
+    Run {
+        input_path: String,
+
+        /// Directory to checkpoint per-client state to, so an interrupted run can be resumed.
+        /// Without it, state only ever lives in memory.
+        #[structopt(long)]
+        store_dir: Option<String>,
+
+        /// Path to write a CSV of rejected transactions to, as `tx_id, client_id, reason`.
+        /// Without it, rejections are only logged.
+        #[structopt(long = "errors")]
+        errors_path: Option<String>,
+    },
+
+    /// Run as a long-lived server, ingesting line-delimited CSV transaction records over TCP.
+    Serve {
+        /// Address to bind to, e.g. `127.0.0.1:8080`.
+        #[structopt(long, default_value = "127.0.0.1:8080")]
+        bind: String,
+
+        /// Directory to checkpoint per-client state to, so an interrupted run can be resumed.
+        /// Without it, state only ever lives in memory.
+        #[structopt(long)]
+        store_dir: Option<String>,
+
+        /// Path to append a CSV of rejected transactions to, as `tx_id, client_id, reason`.
+        /// Without it, rejections are only logged.
+        #[structopt(long = "errors")]
+        errors_path: Option<String>,
+    },
 }
 
 /// Represents a transaction in the CSV input.
@@ -55,8 +96,37 @@ enum TxType {
     Chargeback,
 }
 
+/// Reasons a transaction was rejected instead of applied, returned by `TryFrom<TxRow>` and
+/// `Account::run` instead of being logged ad hoc.
+#[derive(Debug, ThisError)]
+enum TxError {
+    #[error("deposit or withdrawal is lacking amount")]
+    MissingAmount,
+
+    #[error("cannot parse amount as decimal number")]
+    BadAmount(#[from] bigdecimal::ParseBigDecimalError),
+
+    #[error("unknown tx with ID `{0}`")]
+    UnknownTx(u32),
+
+    #[error("insufficient available funds for tx with ID `{0}`")]
+    InsufficientFunds(u32),
+
+    #[error("account is locked")]
+    FrozenAccount,
+
+    #[error("tx with ID `{0}` is already disputed")]
+    AlreadyDisputed(u32),
+
+    #[error("tx with ID `{0}` is not disputed")]
+    NotDisputed(u32),
+
+    #[error("storage error: {0}")]
+    Storage(#[from] Error),
+}
+
 impl TryFrom<TxRow> for Tx {
-    type Error = Error;
+    type Error = TxError;
 
     fn try_from(tx_row: TxRow) -> Result<Self, Self::Error> {
         let TxRow {
@@ -67,25 +137,21 @@ impl TryFrom<TxRow> for Tx {
         } = tx_row;
 
         match (tx_row_type, amount) {
-            (TxRowType::Deposit, Some(amount)) => BigDecimal::from_str(&amount)
-                .context("Cannot parse amount as decimal number")
-                .map(|amount| Tx {
-                    tx_type: TxType::Deposit(amount),
-                    client_id,
-                    tx_id,
-                }),
+            (TxRowType::Deposit, Some(amount)) => Ok(Tx {
+                tx_type: TxType::Deposit(BigDecimal::from_str(&amount)?),
+                client_id,
+                tx_id,
+            }),
 
-            (TxRowType::Deposit, _) => Err(anyhow!("deposit is lacking amount")),
+            (TxRowType::Deposit, None) => Err(TxError::MissingAmount),
 
-            (TxRowType::Withdrawal, Some(amount)) => BigDecimal::from_str(&amount)
-                .context("Cannot parse amount as decimal number")
-                .map(|amount| Tx {
-                    tx_type: TxType::Withdrawal(amount),
-                    client_id,
-                    tx_id,
-                }),
+            (TxRowType::Withdrawal, Some(amount)) => Ok(Tx {
+                tx_type: TxType::Withdrawal(BigDecimal::from_str(&amount)?),
+                client_id,
+                tx_id,
+            }),
 
-            (TxRowType::Withdrawal, _) => Err(anyhow!("withdrawal is lacking amount")),
+            (TxRowType::Withdrawal, None) => Err(TxError::MissingAmount),
 
             (TxRowType::Dispute, _) => Ok(Tx {
                 tx_type: TxType::Dispute,
@@ -108,19 +174,207 @@ impl TryFrom<TxRow> for Tx {
     }
 }
 
-/// Accumulator for folding over domain transactions.
+/// One row of the optional `--errors` CSV side-channel: which transaction was rejected and why.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+struct RejectedTx {
+    tx_id: u32,
+    client_id: u16,
+    reason: String,
+}
+
+/// Abstracts over where account balances and per-transaction dispute history are kept, so a
+/// client's state can live purely in memory or be checkpointed to disk and resumed across
+/// process restarts. Every mutating method returns a `Result`, so storage or corruption errors
+/// propagate up to the caller instead of panicking. Mutations are only required to be visible
+/// to later calls on `self`; a caller that wants them durable calls [`Store::flush`] once it's
+/// done with a batch (`Account::run` flushes once per transaction, not once per field).
+trait Store: Send {
+    fn get_account(&self, client_id: u16) -> Result<Account>;
+
+    fn put_account(&mut self, client_id: u16, account: &Account) -> Result<()>;
+
+    fn record_amount(&mut self, tx_id: u32, stored_tx: StoredTx) -> Result<()>;
+
+    fn get_amount(&self, tx_id: u32) -> Result<Option<StoredTx>>;
+
+    fn load_tx_state(&self, tx_id: u32) -> Result<Option<TxState>>;
+
+    fn put_tx_state(&mut self, tx_id: u32, tx_state: TxState) -> Result<()>;
+
+    /// Persists mutations made so far. A no-op for stores that have no separate durable form.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Keeps all state in memory; nothing survives a process restart.
 #[derive(Debug, Default)]
-struct State {
-    /// Map from client ID to account. Used as actual fold result.
+struct MemoryStore {
     accounts: HashMap<u16, Account>,
+    amounts: HashMap<u32, StoredTx>,
+    tx_state: HashMap<u32, TxState>,
+}
 
-    /// Map from deposit and withdrawal ID to amount.
-    /// Used for backtracking when running dispute, resolve and chargeback transactions.
-    amounts: HashMap<u32, BigDecimal>,
+impl Store for MemoryStore {
+    fn get_account(&self, client_id: u16) -> Result<Account> {
+        Ok(self.accounts.get(&client_id).cloned().unwrap_or_default())
+    }
+
+    fn put_account(&mut self, client_id: u16, account: &Account) -> Result<()> {
+        self.accounts.insert(client_id, account.clone());
+        Ok(())
+    }
+
+    fn record_amount(&mut self, tx_id: u32, stored_tx: StoredTx) -> Result<()> {
+        self.amounts.insert(tx_id, stored_tx);
+        Ok(())
+    }
+
+    fn get_amount(&self, tx_id: u32) -> Result<Option<StoredTx>> {
+        Ok(self.amounts.get(&tx_id).cloned())
+    }
+
+    fn load_tx_state(&self, tx_id: u32) -> Result<Option<TxState>> {
+        Ok(self.tx_state.get(&tx_id).copied())
+    }
+
+    fn put_tx_state(&mut self, tx_id: u32, tx_state: TxState) -> Result<()> {
+        self.tx_state.insert(tx_id, tx_state);
+        Ok(())
+    }
+}
+
+/// One client's account and transaction history, as persisted by a [`FileStore`].
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct ClientRecord {
+    account: Account,
+    amounts: HashMap<u32, StoredTx>,
+    tx_state: HashMap<u32, TxState>,
+}
+
+/// Persists one client's account and transaction history as a JSON file, so a checkpointed run
+/// can be resumed after a process restart by opening a `FileStore` at the same path again.
+///
+/// Resuming by replaying an input file that includes rows already reflected in the checkpoint
+/// will double-apply them: deposits/withdrawals are only guarded against illegal dispute-state
+/// transitions, not against being re-processed under the same `tx_id`. Only feed a resumed run
+/// the portion of the input that wasn't yet processed.
+#[derive(Debug)]
+struct FileStore {
+    path: PathBuf,
+    record: ClientRecord,
+}
+
+impl FileStore {
+    /// Opens the store at `path`, loading any existing checkpoint so a run can resume.
+    fn open(path: PathBuf) -> Result<Self> {
+        let record = if path.exists() {
+            let file = File::open(&path)
+                .context(format!("Cannot open store file `{}`", path.display()))?;
+            serde_json::from_reader(file)
+                .context(format!("Cannot parse store file `{}`", path.display()))?
+        } else {
+            ClientRecord::default()
+        };
+        Ok(Self { path, record })
+    }
+}
+
+impl Store for FileStore {
+    fn get_account(&self, _client_id: u16) -> Result<Account> {
+        Ok(self.record.account.clone())
+    }
+
+    fn put_account(&mut self, _client_id: u16, account: &Account) -> Result<()> {
+        self.record.account = account.clone();
+        Ok(())
+    }
+
+    fn record_amount(&mut self, tx_id: u32, stored_tx: StoredTx) -> Result<()> {
+        self.record.amounts.insert(tx_id, stored_tx);
+        Ok(())
+    }
+
+    fn get_amount(&self, tx_id: u32) -> Result<Option<StoredTx>> {
+        Ok(self.record.amounts.get(&tx_id).cloned())
+    }
+
+    fn load_tx_state(&self, tx_id: u32) -> Result<Option<TxState>> {
+        Ok(self.record.tx_state.get(&tx_id).copied())
+    }
+
+    fn put_tx_state(&mut self, tx_id: u32, tx_state: TxState) -> Result<()> {
+        self.record.tx_state.insert(tx_id, tx_state);
+        Ok(())
+    }
+
+    /// Writes the whole record back to disk in one go. Simple and safe for the toy sizes this
+    /// engine deals with; a high-throughput store would append instead of rewriting.
+    fn flush(&mut self) -> Result<()> {
+        let file = File::create(&self.path)
+            .context(format!("Cannot create store file `{}`", self.path.display()))?;
+        serde_json::to_writer(file, &self.record)
+            .context(format!("Cannot write store file `{}`", self.path.display()))
+    }
+}
+
+/// Builds the store used for one client: a checkpointed [`FileStore`] under `store_dir` when
+/// set, otherwise an in-memory [`MemoryStore`].
+fn store_for(client_id: u16, store_dir: &Option<PathBuf>) -> Result<Box<dyn Store>> {
+    match store_dir {
+        Some(dir) => {
+            let path = dir.join(format!("{client_id}.json"));
+            Ok(Box::new(FileStore::open(path)?))
+        }
+        None => Ok(Box::new(MemoryStore::default())),
+    }
+}
+
+/// Per-client accumulator for folding over one client's ordered transaction stream, backed by a
+/// pluggable [`Store`] so account and transaction history can live in memory or be checkpointed
+/// to disk.
+struct ClientState {
+    client_id: u16,
+    account: Account,
+    store: Box<dyn Store>,
+}
+
+impl ClientState {
+    /// Creates client state, loading the account from `store` so a checkpointed run resumes
+    /// from where it left off instead of starting from a fresh `Account::default()`.
+    fn new(client_id: u16, store: Box<dyn Store>) -> Result<Self> {
+        let account = store.get_account(client_id)?;
+        Ok(Self {
+            client_id,
+            account,
+            store,
+        })
+    }
+
+    fn run(&mut self, tx: Tx) -> Result<(), TxError> {
+        self.account.run(self.client_id, self.store.as_mut(), tx)
+    }
+}
+
+/// A previously processed deposit or withdrawal, recalled when a dispute, resolve or
+/// chargeback references its transaction ID. Only deposits can be disputed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+enum StoredTx {
+    Deposit(BigDecimal),
+    Withdrawal(BigDecimal),
+}
+
+/// The dispute lifecycle of a deposit or withdrawal transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+enum TxState {
+    Processed,
+    Disputed,
+    Resolved,
+    ChargedBack,
 }
 
 /// A domain account.
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 struct Account {
     available: BigDecimal,
     held: BigDecimal,
@@ -129,85 +383,161 @@ struct Account {
 }
 
 impl Account {
-    fn run(&mut self, amounts: &mut HashMap<u32, BigDecimal>, tx: Tx) {
+    fn run(&mut self, client_id: u16, store: &mut dyn Store, tx: Tx) -> Result<(), TxError> {
         let tx_id = tx.tx_id;
 
         match tx.tx_type {
             TxType::Deposit(amount) => {
+                if self.locked {
+                    return Err(TxError::FrozenAccount);
+                }
                 self.available += &amount;
                 self.total += &amount;
-                amounts.insert(tx_id, amount);
+                store.record_amount(tx_id, StoredTx::Deposit(amount))?;
+                store.put_tx_state(tx_id, TxState::Processed)?;
             }
 
             TxType::Withdrawal(amount) => {
+                if self.locked {
+                    return Err(TxError::FrozenAccount);
+                }
                 if self.available < amount {
-                    error!("Insufficient available funds for tx with ID `{tx_id}`");
-                } else {
-                    self.available -= &amount;
-                    self.total -= &amount;
-                    amounts.insert(tx_id, -amount);
+                    return Err(TxError::InsufficientFunds(tx_id));
                 }
+                self.available -= &amount;
+                self.total -= &amount;
+                store.record_amount(tx_id, StoredTx::Withdrawal(amount))?;
+                store.put_tx_state(tx_id, TxState::Processed)?;
             }
 
-            TxType::Dispute => match amounts.get(&tx_id) {
-                Some(amount) => {
-                    self.available -= amount;
-                    self.held += amount;
-                }
-                None => error!("Ignoring dispute for unknown tx with ID `{tx_id}`"),
+            TxType::Dispute => match store.load_tx_state(tx_id)? {
+                Some(TxState::Processed) => match store.get_amount(tx_id)? {
+                    Some(StoredTx::Deposit(amount)) => {
+                        self.available -= &amount;
+                        self.held += &amount;
+                        store.put_tx_state(tx_id, TxState::Disputed)?;
+                    }
+                    Some(StoredTx::Withdrawal(_)) | None => {
+                        return Err(TxError::UnknownTx(tx_id))
+                    }
+                },
+                Some(_) => return Err(TxError::AlreadyDisputed(tx_id)),
+                None => return Err(TxError::UnknownTx(tx_id)),
             },
 
-            TxType::Resolve => match amounts.get(&tx_id) {
-                Some(amount) => {
-                    self.available += amount;
-                    self.held -= amount;
-                }
-                None => error!("Ignoring resolve for unknown tx with ID `{tx_id}`"),
+            TxType::Resolve => match store.load_tx_state(tx_id)? {
+                Some(TxState::Disputed) => match store.get_amount(tx_id)? {
+                    Some(StoredTx::Deposit(amount)) => {
+                        self.available += &amount;
+                        self.held -= &amount;
+                        store.put_tx_state(tx_id, TxState::Resolved)?;
+                    }
+                    Some(StoredTx::Withdrawal(_)) | None => {
+                        return Err(TxError::UnknownTx(tx_id))
+                    }
+                },
+                Some(_) => return Err(TxError::NotDisputed(tx_id)),
+                None => return Err(TxError::UnknownTx(tx_id)),
             },
 
-            TxType::Chargeback => match amounts.get(&tx_id) {
-                Some(amount) => {
-                    self.held -= amount;
-                    self.total -= amount;
-                    self.locked = true;
-                }
-                None => error!("Ignoring dispute for unknown tx with ID `{tx_id}`"),
+            TxType::Chargeback => match store.load_tx_state(tx_id)? {
+                Some(TxState::Disputed) => match store.get_amount(tx_id)? {
+                    Some(StoredTx::Deposit(amount)) => {
+                        self.held -= &amount;
+                        self.total -= &amount;
+                        self.locked = true;
+                        store.put_tx_state(tx_id, TxState::ChargedBack)?;
+                    }
+                    Some(StoredTx::Withdrawal(_)) | None => {
+                        return Err(TxError::UnknownTx(tx_id))
+                    }
+                },
+                Some(_) => return Err(TxError::NotDisputed(tx_id)),
+                None => return Err(TxError::UnknownTx(tx_id)),
             },
         }
+
+        store.put_account(client_id, self)?;
+        store.flush()?;
+        Ok(())
     }
 }
 
-fn main() -> Result<()> {
+#[tokio::main]
+async fn main() -> Result<()> {
     env_logger::init();
 
-    let Opt { input_path } = Opt::from_args();
-
-    let mut reader = ReaderBuilder::new()
-        .trim(Trim::All)
-        .from_path(&input_path)
-        .context(format!("Cannot create reader for `{input_path}`"))?;
+    match Opt::from_args() {
+        Opt::Run {
+            input_path,
+            store_dir,
+            errors_path,
+        } => {
+            let accounts = run(
+                input_path,
+                store_dir.map(PathBuf::from),
+                errors_path.map(PathBuf::from),
+            )
+            .await?;
+            print!("{}", render_accounts(accounts));
+            Ok(())
+        }
+        Opt::Serve {
+            bind,
+            store_dir,
+            errors_path,
+        } => {
+            serve(
+                bind,
+                store_dir.map(PathBuf::from),
+                errors_path.map(PathBuf::from),
+            )
+            .await
+        }
+    }
+}
 
-    let State {
-        accounts,
-        amounts: _,
-    } = reader
-        .deserialize::<TxRow>()
-        .map(|result| result.context("Cannot read/deserialize tx row"))
-        .filter_map(into_tx)
-        .fold(State::default(), run_tx);
+/// Reads and parses the CSV file at `input_path` into a stream of transactions, each either
+/// successfully converted or rejected with a reason, logging and dropping any row that cannot
+/// even be read or deserialized.
+fn tx_stream(input_path: String) -> impl Stream<Item = Result<Tx, RejectedTx>> {
+    stream! {
+        let reader = ReaderBuilder::new()
+            .trim(Trim::All)
+            .from_path(&input_path)
+            .context(format!("Cannot create reader for `{input_path}`"));
 
-    print_accounts(accounts);
+        let mut reader = match reader {
+            Ok(reader) => reader,
+            Err(e) => {
+                error!("{e}");
+                return;
+            }
+        };
 
-    Ok(())
+        for result in reader.deserialize::<TxRow>() {
+            let tx_row = result.context("Cannot read/deserialize tx row");
+            if let Some(tx) = into_tx(tx_row) {
+                yield tx;
+            }
+        }
+    }
 }
 
-fn into_tx(tx_row: Result<TxRow>) -> Option<Tx> {
-    match tx_row.and_then(|row| {
-        let tx_id = row.tx_id;
-        row.try_into()
-            .context(format!("Cannot convert tx row with ID `{tx_id}` into tx"))
-    }) {
-        Ok(tx) => Some(tx),
+/// Converts a read `TxRow` into a `Tx`, or a `RejectedTx` if it is well-formed CSV but an
+/// invalid transaction. Rows that could not even be read/deserialized are logged and dropped,
+/// since they carry no reliable transaction or client ID to report against.
+fn into_tx(tx_row: Result<TxRow>) -> Option<Result<Tx, RejectedTx>> {
+    match tx_row {
+        Ok(row) => {
+            let tx_id = row.tx_id;
+            let client_id = row.client_id;
+            Some(Tx::try_from(row).map_err(|e| RejectedTx {
+                tx_id,
+                client_id,
+                reason: e.to_string(),
+            }))
+        }
         Err(e) => {
             error!("{e}");
             None
@@ -215,21 +545,157 @@ fn into_tx(tx_row: Result<TxRow>) -> Option<Tx> {
     }
 }
 
-fn run_tx(mut state: State, tx: Tx) -> State {
-    match state.accounts.get_mut(&tx.client_id) {
-        Some(account) => account.run(&mut state.amounts, tx),
-        None => {
-            let mut account = Account::default();
-            let client_id = tx.client_id;
-            account.run(&mut state.amounts, tx);
-            state.accounts.insert(client_id, account);
+/// Shards transactions from the CSV file at `input_path` by client ID into per-client tasks,
+/// preserving per-client order while letting different clients' transactions be processed
+/// concurrently, then merges the resulting accounts. `store_dir`, if set, checkpoints every
+/// client's state to disk so an interrupted run can be resumed. `errors_path`, if set, collects
+/// every rejected transaction into a CSV file once the run completes.
+async fn run(
+    input_path: String,
+    store_dir: Option<PathBuf>,
+    errors_path: Option<PathBuf>,
+) -> Result<HashMap<u16, Account>> {
+    let mut senders = HashMap::new();
+    let mut handles: Vec<JoinHandle<Result<(u16, Account)>>> = Vec::new();
+    let (error_tx, mut error_rx) = mpsc::unbounded_channel();
+
+    let mut txs = Box::pin(tx_stream(input_path));
+    while let Some(result) = txs.next().await {
+        let tx = match result {
+            Ok(tx) => tx,
+            Err(rejected) => {
+                warn!(
+                    "Rejecting tx with ID `{}` for client `{}`: {}",
+                    rejected.tx_id, rejected.client_id, rejected.reason
+                );
+                let _ = error_tx.send(rejected);
+                continue;
+            }
+        };
+
+        let client_id = tx.client_id;
+        let sender = match senders.entry(client_id) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let store = store_for(client_id, &store_dir)?;
+                let (sender, receiver) = mpsc::unbounded_channel();
+                handles.push(tokio::spawn(run_client(
+                    client_id,
+                    receiver,
+                    store,
+                    error_tx.clone(),
+                )));
+                entry.insert(sender)
+            }
+        };
+        if sender.send(tx).is_err() {
+            error!("Cannot send tx to task for client `{client_id}`: task has terminated");
+        }
+    }
+
+    // Dropping the senders closes every per-client channel, letting the tasks finish.
+    drop(senders);
+    drop(error_tx);
+
+    let mut accounts = HashMap::new();
+    let mut task_error = None;
+    for handle in handles {
+        match handle.await {
+            Ok(Ok((client_id, account))) => {
+                accounts.insert(client_id, account);
+            }
+            Ok(Err(e)) => {
+                error!("Task for client failed: {e}");
+                task_error.get_or_insert(e);
+            }
+            Err(e) => error!("Task for client panicked: {e}"),
+        }
+    }
+
+    let mut rejected = Vec::new();
+    while let Some(r) = error_rx.recv().await {
+        rejected.push(r);
+    }
+    if let Some(path) = errors_path {
+        write_rejected_csv(&path, &rejected)?;
+    }
+
+    // A storage failure in any client task means its final balance can't be trusted; surface it
+    // to `main` instead of silently emitting an incomplete account set.
+    if let Some(e) = task_error {
+        return Err(e);
+    }
+
+    Ok(accounts)
+}
+
+/// Runs one client's ordered transaction stream to completion, returning its final account.
+/// Rejected transactions are logged and reported on `errors`; only a storage failure aborts the
+/// task early.
+async fn run_client(
+    client_id: u16,
+    mut txs: mpsc::UnboundedReceiver<Tx>,
+    store: Box<dyn Store>,
+    errors: mpsc::UnboundedSender<RejectedTx>,
+) -> Result<(u16, Account)> {
+    let mut state = ClientState::new(client_id, store)?;
+    while let Some(tx) = txs.recv().await {
+        let tx_id = tx.tx_id;
+        match state.run(tx) {
+            Ok(()) => {}
+            Err(TxError::Storage(e)) => return Err(e),
+            Err(e) => {
+                warn!("Rejecting tx with ID `{tx_id}` for client `{client_id}`: {e}");
+                let _ = errors.send(RejectedTx {
+                    tx_id,
+                    client_id,
+                    reason: e.to_string(),
+                });
+            }
         }
     }
-    state
+    Ok((client_id, state.account))
 }
 
-fn print_accounts(accounts: HashMap<u16, Account>) {
-    println!("client, available, held, total, locked");
+/// Writes every rejected transaction collected during a run to the CSV file at `path`.
+fn write_rejected_csv(path: &Path, rejected: &[RejectedTx]) -> Result<()> {
+    let file =
+        File::create(path).context(format!("Cannot create errors file `{}`", path.display()))?;
+    let mut writer = csv::Writer::from_writer(file);
+    for r in rejected {
+        writer.serialize(r)?;
+    }
+    writer
+        .flush()
+        .context(format!("Cannot flush errors file `{}`", path.display()))
+}
+
+/// Appends a single rejected transaction to the CSV file at `path`, writing the header first if
+/// the file doesn't exist yet.
+fn append_rejected(path: &Path, rejected: &RejectedTx) -> Result<()> {
+    let is_new = !path.exists();
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .context(format!("Cannot open errors file `{}`", path.display()))?;
+    let mut writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(file);
+    if is_new {
+        writer.write_record(["tx_id", "client_id", "reason"])?;
+    }
+    writer.serialize(rejected)?;
+    writer
+        .flush()
+        .context(format!("Cannot flush errors file `{}`", path.display()))
+}
+
+/// Renders a snapshot of accounts as CSV, matching the expected output format.
+fn render_accounts(accounts: HashMap<u16, Account>) -> String {
+    use std::fmt::Write;
+
+    let mut report = String::from("client, available, held, total, locked\n");
     for (
         client_id,
         Account {
@@ -240,13 +706,219 @@ fn print_accounts(accounts: HashMap<u16, Account>) {
         },
     ) in accounts
     {
-        println!("{client_id}, {available:.4}, {held:.4}, {total:.4}, {locked}")
+        writeln!(report, "{client_id}, {available:.4}, {held:.4}, {total:.4}, {locked}")
+            .expect("Cannot write to string");
+    }
+    report
+}
+
+/// Runs the engine as a long-lived TCP server: every connection feeds line-delimited CSV
+/// transaction records into the shared engine state, and a `snapshot` line dumps the current
+/// accounts back over the same connection.
+async fn serve(
+    bind: String,
+    store_dir: Option<PathBuf>,
+    errors_path: Option<PathBuf>,
+) -> Result<()> {
+    let listener = TcpListener::bind(&bind)
+        .await
+        .context(format!("Cannot bind to `{bind}`"))?;
+    let engine = Engine::new(store_dir, errors_path);
+
+    loop {
+        let (socket, addr) = listener
+            .accept()
+            .await
+            .context("Cannot accept connection")?;
+        let engine = engine.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(socket, engine).await {
+                error!("Connection from `{addr}` failed: {e}");
+            }
+        });
     }
 }
 
+/// Per-client channel senders, keyed by client ID; each carries a transaction plus an ack sender
+/// that the receiving task fires once the transaction has been applied.
+type ClientSenders = HashMap<u16, mpsc::UnboundedSender<(Tx, oneshot::Sender<()>)>>;
+
+/// Shared, live engine state for server mode: one channel per client feeding its task, and the
+/// accounts those tasks maintain, readable at any time for a snapshot.
+#[derive(Debug, Clone)]
+struct Engine {
+    senders: Arc<Mutex<ClientSenders>>,
+    accounts: Arc<Mutex<HashMap<u16, Account>>>,
+    store_dir: Option<PathBuf>,
+    errors_path: Option<PathBuf>,
+}
+
+impl Engine {
+    fn new(store_dir: Option<PathBuf>, errors_path: Option<PathBuf>) -> Self {
+        Self {
+            senders: Arc::default(),
+            accounts: Arc::default(),
+            store_dir,
+            errors_path,
+        }
+    }
+
+    /// Hands a transaction off to its client's task, spawning that task on first use. Returns a
+    /// receiver that resolves once the task has applied (or rejected) the transaction, so a
+    /// caller can await it before relying on `snapshot` to reflect it.
+    fn dispatch(&self, tx: Tx) -> oneshot::Receiver<()> {
+        let (ack_tx, ack_rx) = oneshot::channel();
+        let client_id = tx.client_id;
+        let mut senders = self.senders.lock().expect("Senders mutex poisoned");
+        let sender = senders.entry(client_id).or_insert_with(|| {
+            let (sender, receiver) = mpsc::unbounded_channel();
+            tokio::spawn(run_client_live(
+                client_id,
+                receiver,
+                Arc::clone(&self.accounts),
+                self.store_dir.clone(),
+                self.errors_path.clone(),
+            ));
+            sender
+        });
+        if sender.send((tx, ack_tx)).is_err() {
+            error!("Cannot send tx to task for client `{client_id}`: task has terminated");
+        }
+        ack_rx
+    }
+
+    /// Returns a snapshot of the accounts as currently known.
+    fn snapshot(&self) -> HashMap<u16, Account> {
+        self.accounts
+            .lock()
+            .expect("Accounts mutex poisoned")
+            .clone()
+    }
+
+    /// Records a transaction that was rejected before reaching a client task, e.g. one that
+    /// failed to parse.
+    fn record_rejected(&self, rejected: RejectedTx) -> Result<()> {
+        warn!(
+            "Rejecting tx with ID `{}` for client `{}`: {}",
+            rejected.tx_id, rejected.client_id, rejected.reason
+        );
+        match &self.errors_path {
+            Some(path) => append_rejected(path, &rejected),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Like [`run_client`], but publishes the account into `accounts` after every transaction so a
+/// concurrent snapshot request sees up-to-date state instead of only the final result. Each
+/// transaction's ack is sent only after that publish, so a caller that awaits it before issuing
+/// `snapshot` is guaranteed to observe the transaction.
+async fn run_client_live(
+    client_id: u16,
+    mut txs: mpsc::UnboundedReceiver<(Tx, oneshot::Sender<()>)>,
+    accounts: Arc<Mutex<HashMap<u16, Account>>>,
+    store_dir: Option<PathBuf>,
+    errors_path: Option<PathBuf>,
+) {
+    let store = match store_for(client_id, &store_dir) {
+        Ok(store) => store,
+        Err(e) => {
+            error!("Cannot open store for client `{client_id}`: {e}");
+            return;
+        }
+    };
+    let mut state = match ClientState::new(client_id, store) {
+        Ok(state) => state,
+        Err(e) => {
+            error!("Cannot initialize state for client `{client_id}`: {e}");
+            return;
+        }
+    };
+
+    while let Some((tx, ack)) = txs.recv().await {
+        let tx_id = tx.tx_id;
+        match state.run(tx) {
+            Ok(()) => {
+                accounts
+                    .lock()
+                    .expect("Accounts mutex poisoned")
+                    .insert(client_id, state.account.clone());
+            }
+            Err(TxError::Storage(e)) => {
+                error!("Storage failure for client `{client_id}`: {e}");
+                return;
+            }
+            Err(e) => {
+                warn!("Rejecting tx with ID `{tx_id}` for client `{client_id}`: {e}");
+                if let Some(path) = &errors_path {
+                    let rejected = RejectedTx {
+                        tx_id,
+                        client_id,
+                        reason: e.to_string(),
+                    };
+                    if let Err(e) = append_rejected(path, &rejected) {
+                        error!("Cannot record rejected tx with ID `{tx_id}`: {e}");
+                    }
+                }
+            }
+        }
+        let _ = ack.send(());
+    }
+}
+
+async fn handle_connection(socket: TcpStream, engine: Engine) -> Result<()> {
+    let (reader, mut writer) = socket.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines
+        .next_line()
+        .await
+        .context("Cannot read line from socket")?
+    {
+        let line = line.trim();
+        if line.eq_ignore_ascii_case("snapshot") {
+            let report = render_accounts(engine.snapshot());
+            writer
+                .write_all(report.as_bytes())
+                .await
+                .context("Cannot write snapshot to socket")?;
+        } else if !line.is_empty() {
+            match into_tx(parse_tx_line(line)) {
+                Some(Ok(tx)) => {
+                    // Wait for the client task to apply this transaction before reading the
+                    // next line, so a `snapshot` later on this connection reflects it.
+                    let _ = engine.dispatch(tx).await;
+                }
+                Some(Err(rejected)) => engine.record_rejected(rejected)?,
+                None => {}
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Parses a single line-delimited CSV transaction record, i.e. a `TxRow` without the header row
+/// that a file-based [`tx_stream`] would otherwise rely on.
+fn parse_tx_line(line: &str) -> Result<TxRow> {
+    let headers = csv::StringRecord::from(vec!["type", "client", "tx", "amount"]);
+    let mut reader = ReaderBuilder::new()
+        .has_headers(false)
+        .trim(Trim::All)
+        .from_reader(line.as_bytes());
+    reader
+        .records()
+        .next()
+        .context("Empty tx record")?
+        .context("Cannot read CSV record")?
+        .deserialize(Some(&headers))
+        .context("Cannot deserialize tx record")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use anyhow::anyhow;
 
     #[test]
     fn test_into_tx_ok_deposit() {
@@ -257,15 +929,13 @@ mod tests {
             amount: Some("1.2345".to_string()),
         });
         let tx = into_tx(tx_row);
-        assert!(tx.is_some());
-        let tx = tx.unwrap();
         assert_eq!(
             tx,
-            Tx {
+            Some(Ok(Tx {
                 tx_type: TxType::Deposit(amount_12345()),
                 client_id: 42,
                 tx_id: 666
-            }
+            }))
         )
     }
 
@@ -278,15 +948,13 @@ mod tests {
             amount: Some("1.2345".to_string()),
         });
         let tx = into_tx(tx_row);
-        assert!(tx.is_some());
-        let tx = tx.unwrap();
         assert_eq!(
             tx,
-            Tx {
+            Some(Ok(Tx {
                 tx_type: TxType::Withdrawal(amount_12345()),
                 client_id: 42,
                 tx_id: 666
-            }
+            }))
         )
     }
 
@@ -299,7 +967,15 @@ mod tests {
             amount: Some("INVALID".to_string()),
         });
         let tx = into_tx(tx_row);
-        assert!(tx.is_none());
+        assert_eq!(
+            tx,
+            Some(Err(RejectedTx {
+                tx_id: 666,
+                client_id: 42,
+                reason: TxError::BadAmount(BigDecimal::from_str("INVALID").unwrap_err())
+                    .to_string(),
+            }))
+        );
     }
 
     #[test]
@@ -311,7 +987,14 @@ mod tests {
             amount: None,
         });
         let tx = into_tx(tx_row);
-        assert!(tx.is_none());
+        assert_eq!(
+            tx,
+            Some(Err(RejectedTx {
+                tx_id: 666,
+                client_id: 42,
+                reason: TxError::MissingAmount.to_string(),
+            }))
+        );
     }
 
     #[test]
@@ -322,182 +1005,390 @@ mod tests {
     }
 
     #[test]
-    fn test_run_tx_deposit() {
-        let state = State::default();
-        let tx = Tx {
-            tx_type: TxType::Deposit(amount_12345()),
-            client_id: 42,
-            tx_id: 666,
-        };
-        let State { accounts, amounts } = run_tx(state, tx);
+    fn test_account_run_deposit() {
+        let mut store = MemoryStore::default();
+        let mut account = Account::default();
+        account
+            .run(
+                42,
+                &mut store,
+                Tx {
+                    tx_type: TxType::Deposit(amount_12345()),
+                    client_id: 42,
+                    tx_id: 666,
+                },
+            )
+            .unwrap();
         assert_eq!(
-            accounts.get(&42),
-            Some(&Account {
+            account,
+            Account {
                 available: amount_12345(),
                 held: BigDecimal::default(),
                 total: amount_12345(),
                 locked: false,
-            })
+            }
+        );
+        assert_eq!(
+            store.get_amount(666).unwrap(),
+            Some(StoredTx::Deposit(amount_12345()))
         );
-        assert_eq!(amounts.get(&666), Some(&amount_12345()));
+        assert_eq!(store.get_account(42).unwrap(), account);
     }
 
     #[test]
-    fn test_run_tx_withdrawal_insufficient_available() {
-        let state = State::default();
-        let tx = Tx {
-            tx_type: TxType::Withdrawal(amount_12345()),
-            client_id: 42,
-            tx_id: 666,
+    fn test_account_run_withdrawal_insufficient_available() {
+        let mut store = MemoryStore::default();
+        let mut account = Account::default();
+        let err = account
+            .run(
+                42,
+                &mut store,
+                Tx {
+                    tx_type: TxType::Withdrawal(amount_12345()),
+                    client_id: 42,
+                    tx_id: 666,
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(err, TxError::InsufficientFunds(666)));
+        assert_eq!(account, Account::default());
+        assert_eq!(store.get_amount(666).unwrap(), None);
+    }
+
+    #[test]
+    fn test_account_run_withdrawal() {
+        let mut store = MemoryStore::default();
+        store
+            .record_amount(666, StoredTx::Deposit(amount_12345()))
+            .unwrap();
+        store.put_tx_state(666, TxState::Processed).unwrap();
+
+        let mut account = Account {
+            available: amount_12345(),
+            held: BigDecimal::default(),
+            total: amount_12345(),
+            locked: false,
         };
-        let State { accounts, amounts } = run_tx(state, tx);
+        account
+            .run(
+                42,
+                &mut store,
+                Tx {
+                    tx_type: TxType::Withdrawal(BigDecimal::from_str("0.2345").unwrap()),
+                    client_id: 42,
+                    tx_id: 999,
+                },
+            )
+            .unwrap();
         assert_eq!(
-            accounts.get(&42),
-            Some(&Account {
-                available: BigDecimal::default(),
+            account,
+            Account {
+                available: BigDecimal::from_str("1").unwrap(),
                 held: BigDecimal::default(),
-                total: BigDecimal::default(),
+                total: BigDecimal::from_str("1").unwrap(),
                 locked: false,
-            })
+            }
+        );
+        assert_eq!(
+            store.get_amount(999).unwrap(),
+            Some(StoredTx::Withdrawal(BigDecimal::from_str("0.2345").unwrap()))
         );
-        assert_eq!(amounts.get(&666), None);
     }
 
     #[test]
-    fn test_run_tx_withdrawal() {
-        let mut state = State::default();
-        state.accounts.insert(
-            42,
+    fn test_account_run_dispute() {
+        let mut store = MemoryStore::default();
+        store
+            .record_amount(666, StoredTx::Deposit(amount_12345()))
+            .unwrap();
+        store.put_tx_state(666, TxState::Processed).unwrap();
+
+        let mut account = Account {
+            available: amount_12345(),
+            held: BigDecimal::default(),
+            total: amount_12345(),
+            locked: false,
+        };
+        account
+            .run(
+                42,
+                &mut store,
+                Tx {
+                    tx_type: TxType::Dispute,
+                    client_id: 42,
+                    tx_id: 666,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            account,
             Account {
-                available: amount_12345(),
-                held: BigDecimal::default(),
+                available: BigDecimal::default(),
+                held: amount_12345(),
                 total: amount_12345(),
                 locked: false,
-            },
+            }
         );
-        state.amounts.insert(666, amount_12345());
+        assert_eq!(store.load_tx_state(666).unwrap(), Some(TxState::Disputed));
+    }
 
-        let tx = Tx {
-            tx_type: TxType::Withdrawal(BigDecimal::from_str("0.2345").unwrap()),
-            client_id: 42,
-            tx_id: 999,
+    #[test]
+    fn test_account_run_dispute_ignored_when_not_processed() {
+        let mut store = MemoryStore::default();
+        store
+            .record_amount(666, StoredTx::Deposit(amount_12345()))
+            .unwrap();
+        store.put_tx_state(666, TxState::Disputed).unwrap();
+
+        let mut account = Account {
+            available: amount_12345(),
+            held: BigDecimal::default(),
+            total: amount_12345(),
+            locked: false,
         };
-        let State { accounts, amounts } = run_tx(state, tx);
+        let err = account
+            .run(
+                42,
+                &mut store,
+                Tx {
+                    tx_type: TxType::Dispute,
+                    client_id: 42,
+                    tx_id: 666,
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(err, TxError::AlreadyDisputed(666)));
         assert_eq!(
-            accounts.get(&42),
-            Some(&Account {
-                available: BigDecimal::from_str("1").unwrap(),
+            account,
+            Account {
+                available: amount_12345(),
                 held: BigDecimal::default(),
-                total: BigDecimal::from_str("1").unwrap(),
+                total: amount_12345(),
                 locked: false,
-            })
-        );
-        assert_eq!(amounts.get(&666), Some(&amount_12345()));
-        assert_eq!(
-            amounts.get(&999),
-            Some(&BigDecimal::from_str("-0.2345").unwrap())
+            }
         );
+        assert_eq!(store.load_tx_state(666).unwrap(), Some(TxState::Disputed));
     }
 
     #[test]
-    fn test_run_tx_dispute() {
-        let mut state = State::default();
-        state.accounts.insert(
-            42,
+    fn test_account_run_resolve() {
+        let mut store = MemoryStore::default();
+        store
+            .record_amount(666, StoredTx::Deposit(amount_12345()))
+            .unwrap();
+        store.put_tx_state(666, TxState::Disputed).unwrap();
+
+        let mut account = Account {
+            available: BigDecimal::default(),
+            held: amount_12345(),
+            total: amount_12345(),
+            locked: false,
+        };
+        account
+            .run(
+                42,
+                &mut store,
+                Tx {
+                    tx_type: TxType::Resolve,
+                    client_id: 42,
+                    tx_id: 666,
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            account,
             Account {
                 available: amount_12345(),
                 held: BigDecimal::default(),
                 total: amount_12345(),
                 locked: false,
-            },
+            }
         );
-        state.amounts.insert(666, amount_12345());
+        assert_eq!(store.load_tx_state(666).unwrap(), Some(TxState::Resolved));
+    }
 
-        let tx = Tx {
-            tx_type: TxType::Dispute,
-            client_id: 42,
-            tx_id: 666,
+    #[test]
+    fn test_account_run_chargeback() {
+        let mut store = MemoryStore::default();
+        store
+            .record_amount(666, StoredTx::Deposit(amount_12345()))
+            .unwrap();
+        store.put_tx_state(666, TxState::Disputed).unwrap();
+
+        let mut account = Account {
+            available: BigDecimal::default(),
+            held: amount_12345(),
+            total: amount_12345(),
+            locked: false,
         };
-        let State {
-            accounts,
-            amounts: _,
-        } = run_tx(state, tx);
+        account
+            .run(
+                42,
+                &mut store,
+                Tx {
+                    tx_type: TxType::Chargeback,
+                    client_id: 42,
+                    tx_id: 666,
+                },
+            )
+            .unwrap();
         assert_eq!(
-            accounts.get(&42),
-            Some(&Account {
+            account,
+            Account {
                 available: BigDecimal::default(),
-                held: amount_12345(),
-                total: amount_12345(),
-                locked: false,
-            })
+                held: BigDecimal::default(),
+                total: BigDecimal::default(),
+                locked: true,
+            }
+        );
+        assert_eq!(
+            store.load_tx_state(666).unwrap(),
+            Some(TxState::ChargedBack)
         );
     }
 
     #[test]
-    fn test_run_tx_resolve() {
-        let mut state = State::default();
-        state.accounts.insert(
-            42,
+    fn test_account_run_dispute_ignored_for_withdrawal() {
+        let mut store = MemoryStore::default();
+        store
+            .record_amount(666, StoredTx::Withdrawal(amount_12345()))
+            .unwrap();
+        store.put_tx_state(666, TxState::Processed).unwrap();
+
+        let mut account = Account::default();
+        let err = account
+            .run(
+                42,
+                &mut store,
+                Tx {
+                    tx_type: TxType::Dispute,
+                    client_id: 42,
+                    tx_id: 666,
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(err, TxError::UnknownTx(666)));
+        assert_eq!(account, Account::default());
+        assert_eq!(store.load_tx_state(666).unwrap(), Some(TxState::Processed));
+    }
+
+    #[test]
+    fn test_account_run_deposit_ignored_for_locked_account() {
+        let mut store = MemoryStore::default();
+        let mut account = Account {
+            locked: true,
+            ..Account::default()
+        };
+        let err = account
+            .run(
+                42,
+                &mut store,
+                Tx {
+                    tx_type: TxType::Deposit(amount_12345()),
+                    client_id: 42,
+                    tx_id: 666,
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(err, TxError::FrozenAccount));
+        assert_eq!(
+            account,
             Account {
-                available: BigDecimal::default(),
-                held: amount_12345(),
-                total: amount_12345(),
-                locked: false,
-            },
+                locked: true,
+                ..Account::default()
+            }
         );
-        state.amounts.insert(666, amount_12345());
+        assert_eq!(store.get_amount(666).unwrap(), None);
+    }
 
-        let tx = Tx {
-            tx_type: TxType::Resolve,
-            client_id: 42,
-            tx_id: 666,
+    #[test]
+    fn test_account_run_withdrawal_ignored_for_locked_account() {
+        let mut store = MemoryStore::default();
+        let mut account = Account {
+            available: amount_12345(),
+            held: BigDecimal::default(),
+            total: amount_12345(),
+            locked: true,
         };
-        let State {
-            accounts,
-            amounts: _,
-        } = run_tx(state, tx);
+        let err = account
+            .run(
+                42,
+                &mut store,
+                Tx {
+                    tx_type: TxType::Withdrawal(amount_12345()),
+                    client_id: 42,
+                    tx_id: 666,
+                },
+            )
+            .unwrap_err();
+        assert!(matches!(err, TxError::FrozenAccount));
         assert_eq!(
-            accounts.get(&42),
-            Some(&Account {
+            account,
+            Account {
                 available: amount_12345(),
                 held: BigDecimal::default(),
                 total: amount_12345(),
-                locked: false,
-            })
+                locked: true,
+            }
         );
+        assert_eq!(store.get_amount(666).unwrap(), None);
+    }
+
+    #[test]
+    fn test_client_state_resumes_from_store() {
+        let mut store = MemoryStore::default();
+        store
+            .put_account(
+                42,
+                &Account {
+                    available: amount_12345(),
+                    held: BigDecimal::default(),
+                    total: amount_12345(),
+                    locked: false,
+                },
+            )
+            .unwrap();
+
+        let state = ClientState::new(42, Box::new(store)).unwrap();
+        assert_eq!(state.account.available, amount_12345());
     }
 
     #[test]
-    fn test_run_tx_chargeback() {
-        let mut state = State::default();
-        state.accounts.insert(
+    fn test_parse_tx_line_deposit() {
+        let tx_row = parse_tx_line("deposit, 42, 666, 1.2345");
+        assert!(tx_row.is_ok());
+        let tx = into_tx(tx_row);
+        assert_eq!(
+            tx,
+            Some(Ok(Tx {
+                tx_type: TxType::Deposit(amount_12345()),
+                client_id: 42,
+                tx_id: 666,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_parse_tx_line_invalid() {
+        let tx_row = parse_tx_line("not, a, valid, record, at, all");
+        assert!(tx_row.is_err());
+    }
+
+    #[test]
+    fn test_render_accounts() {
+        let mut accounts = HashMap::new();
+        accounts.insert(
             42,
             Account {
-                available: BigDecimal::default(),
-                held: amount_12345(),
+                available: amount_12345(),
+                held: BigDecimal::default(),
                 total: amount_12345(),
                 locked: false,
             },
         );
-        state.amounts.insert(666, amount_12345());
-
-        let tx = Tx {
-            tx_type: TxType::Chargeback,
-            client_id: 42,
-            tx_id: 666,
-        };
-        let State {
-            accounts,
-            amounts: _,
-        } = run_tx(state, tx);
         assert_eq!(
-            accounts.get(&42),
-            Some(&Account {
-                available: BigDecimal::default(),
-                held: BigDecimal::default(),
-                total: BigDecimal::default(),
-                locked: true,
-            })
+            render_accounts(accounts),
+            "client, available, held, total, locked\n42, 1.2345, 0.0000, 1.2345, false\n"
         );
     }
 